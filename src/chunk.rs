@@ -1,19 +1,39 @@
+use bytes::{ Buf, BufMut, Bytes };
 use crc::{ Crc, CRC_32_ISO_HDLC };
 use std::convert::TryFrom;
 use std::fmt;
-use std::io::{ BufReader, Read };
+use std::io::Read;
+use std::str::FromStr;
 
 use crate::{ Error, Result };
 use crate::chunk_type::ChunkType;
 
+/// The largest `length` field a chunk may declare, per the PNG spec (2^31 - 1).
+pub const MAX_CHUNK_LEN: u32 = (1 << 31) - 1;
+
+const CRC32: Crc<u32> = Crc::<u32>::new(&CRC_32_ISO_HDLC);
+
+/// Computes the PNG CRC over a chunk type and data without ever
+/// concatenating them into a scratch buffer: the digest is fed the type
+/// bytes, then the (possibly large) data slice, directly.
+fn chunk_crc(chunktype: &ChunkType, data: &[u8]) -> u32 {
+    let mut digest = CRC32.digest();
+    digest.update(&chunktype.bytes());
+    digest.update(data);
+    digest.finalize()
+}
+
 /// A validated PNG chunk. See the PNG Spec for more details
 /// http://www.libpng.org/pub/png/spec/1.2/PNG-Structure.html
+///
+/// `chunk_data` is `Bytes` rather than `Vec<u8>` so cloning a `Chunk` or
+/// slicing out its payload is a cheap refcount bump instead of a copy.
 #[derive(Debug, Clone)]
 pub struct Chunk {
     // Write me!
     len: u32,
     chunktype: ChunkType,
-    chunk_data: Vec<u8>,
+    chunk_data: Bytes,
     chunk_crc: u32,
 }
 
@@ -21,23 +41,12 @@ impl Chunk {
     /// New a Chunk
     pub fn new(chunktype_init: ChunkType, data_init: Vec<u8>) -> Chunk {
         let len_tmp: u32 = data_init.len() as u32;
-        let bytes_type = chunktype_init.bytes();
-        let mut check_crc: Vec<u8> = vec![
-            bytes_type[0],
-            bytes_type[1],
-            bytes_type[2],
-            bytes_type[3]
-        ];
-        for i in &data_init {
-            check_crc.push(*i);
-        }
-        const CRC32: Crc<u32> = Crc::<u32>::new(&CRC_32_ISO_HDLC);
-        let checksum = CRC32.checksum(&check_crc);
+        let checksum = chunk_crc(&chunktype_init, &data_init);
 
         Chunk {
             len: len_tmp,
             chunktype: chunktype_init,
-            chunk_data: data_init,
+            chunk_data: Bytes::from(data_init),
             chunk_crc: checksum,
         }
     }
@@ -66,10 +75,66 @@ impl Chunk {
     /// if the stored data is not valid UTF-8.
     pub fn data_as_string(&self) -> Result<String> {
         let d = self.chunk_data.clone();
-        let ans = String::from_utf8(d)?;
+        let ans = String::from_utf8(d.to_vec())?;
         Ok(ans)
     }
 
+    /// Reads one chunk from `reader`, rejecting any length field larger than
+    /// [`MAX_CHUNK_LEN`]. See [`Chunk::from_reader_with_max_len`] if a
+    /// different cap is needed.
+    pub fn from_reader<R: Read>(reader: &mut R) -> Result<Chunk> {
+        Chunk::from_reader_with_max_len(reader, MAX_CHUNK_LEN)
+    }
+
+    /// Reads one chunk from `reader`, reading the 4-byte length, the 4-byte
+    /// type, exactly `length` data bytes and the 4-byte CRC in order, and
+    /// validating the CRC against the type and data.
+    ///
+    /// `max_chunk_len` bounds the `length` field before any allocation is
+    /// made, so a corrupt or malicious length can't be used to make us try
+    /// to allocate an unreasonably large `Vec`.
+    pub fn from_reader_with_max_len<R: Read>(
+        reader: &mut R,
+        max_chunk_len: u32
+    ) -> Result<Chunk> {
+        let mut len_bytes = [0u8; 4];
+        reader.read_exact(&mut len_bytes)?;
+        let len_tmp = u32::from_be_bytes(len_bytes);
+        if len_tmp > max_chunk_len {
+            return Err(
+                format!(
+                    "Chunk From Reader Error: length {} exceeds max_chunk_len {}",
+                    len_tmp,
+                    max_chunk_len
+                ).into()
+            );
+        }
+
+        let mut chunk_type_bytes = [0u8; 4];
+        reader.read_exact(&mut chunk_type_bytes)?;
+        let chunk_type_tmp = ChunkType::try_from(chunk_type_bytes)?;
+
+        let mut data_tmp: Vec<u8> = vec![0u8; len_tmp as usize];
+        reader.read_exact(&mut data_tmp)?;
+
+        let mut chunk_crc_bytes = [0u8; 4];
+        reader.read_exact(&mut chunk_crc_bytes)?;
+        let chunk_crc_tmp = u32::from_be_bytes(chunk_crc_bytes);
+
+        let checksum = chunk_crc(&chunk_type_tmp, &data_tmp);
+
+        if checksum != chunk_crc_tmp {
+            return Err("Chunk From Reader Error: crc not match".into());
+        }
+
+        Ok(Chunk {
+            len: len_tmp,
+            chunktype: chunk_type_tmp,
+            chunk_data: Bytes::from(data_tmp),
+            chunk_crc: chunk_crc_tmp,
+        })
+    }
+
     /// Returns this chunk as a byte sequences described by the PNG spec.
     /// The following data is included in this byte sequence in order:
     /// 1. Length of the data *(4 bytes)*
@@ -77,26 +142,262 @@ impl Chunk {
     /// 3. The data itself *(`length` bytes)*
     /// 4. The CRC of the chunk type and data *(4 bytes)*
     pub fn as_bytes(&self) -> Vec<u8> {
-        let mut ans: Vec<u8> = vec![];
-        let len_tmp = u32::to_be_bytes(self.len);
-        // 加入length
-        for i in len_tmp {
-            ans.push(i);
+        let mut ans: Vec<u8> = Vec::with_capacity(12 + self.chunk_data.len());
+        self.encode(&mut ans);
+        ans
+    }
+
+    /// Writes this chunk's length, type, data and CRC directly into `buf`,
+    /// in the order described by [`Chunk::as_bytes`]. Unlike `as_bytes`,
+    /// this lets a caller serialize many chunks into one growable buffer
+    /// without an intermediate `Vec` allocation per chunk.
+    pub fn encode<B: BufMut>(&self, buf: &mut B) {
+        buf.put_u32(self.len);
+        buf.put_slice(&self.chunktype.bytes());
+        buf.put_slice(&self.chunk_data);
+        buf.put_u32(self.chunk_crc);
+    }
+
+    /// The `encode` counterpart: reads one chunk's length, type, data and
+    /// CRC out of `buf`, validating the CRC. If `buf` is backed by `Bytes`,
+    /// the chunk's data is sliced out rather than copied.
+    pub fn decode<B: Buf>(buf: &mut B) -> Result<Chunk> {
+        if buf.remaining() < 8 {
+            return Err("Chunk Decode Error: buf not long enough".into());
         }
-        // 加入chunk type
-        for j in self.chunktype.bytes() {
-            ans.push(j);
+        let len_tmp = buf.get_u32();
+        if len_tmp > MAX_CHUNK_LEN {
+            return Err(
+                format!(
+                    "Chunk Decode Error: length {} exceeds max_chunk_len {}",
+                    len_tmp,
+                    MAX_CHUNK_LEN
+                ).into()
+            );
         }
-        // 加入data
-        ans.append(&mut self.chunk_data.clone());
-        // 加入crc
-        let crc_tmp = u32::to_be_bytes(self.chunk_crc);
-        for k in crc_tmp {
-            ans.push(k);
+
+        let mut chunk_type_bytes = [0u8; 4];
+        buf.copy_to_slice(&mut chunk_type_bytes);
+        let chunk_type_tmp = ChunkType::try_from(chunk_type_bytes)?;
+
+        if (buf.remaining() as u64) < (len_tmp as u64) + 4 {
+            return Err("Chunk Decode Error: buf not long enough".into());
         }
+        let data_tmp = buf.copy_to_bytes(len_tmp as usize);
+        let chunk_crc_tmp = buf.get_u32();
 
-        ans
+        let checksum = chunk_crc(&chunk_type_tmp, &data_tmp);
+
+        if checksum != chunk_crc_tmp {
+            return Err("Chunk Decode Error: crc not match".into());
+        }
+
+        Ok(Chunk {
+            len: len_tmp,
+            chunktype: chunk_type_tmp,
+            chunk_data: data_tmp,
+            chunk_crc: chunk_crc_tmp,
+        })
+    }
+
+    /// Decodes this chunk's payload as one of the standard PNG text chunks,
+    /// based on its `chunk_type`. Returns an error if the type isn't one of
+    /// `tEXt`, `zTXt` or `iTXt`, or if the payload isn't framed correctly.
+    pub fn parse_payload(&self) -> Result<ChunkPayload> {
+        let data = self.data();
+        match self.chunk_type().to_string().as_str() {
+            "tEXt" => {
+                let null_pos = find_null(data)?;
+                let keyword = latin1_to_string(&data[..null_pos]);
+                let text = latin1_to_string(&data[null_pos + 1..]);
+                Ok(ChunkPayload::Text { keyword, text })
+            }
+            "zTXt" => {
+                let null_pos = find_null(data)?;
+                let keyword = latin1_to_string(&data[..null_pos]);
+                if data.len() < null_pos + 2 {
+                    return Err("Chunk Parse Payload Error: zTXt missing compression method".into());
+                }
+                // data[null_pos + 1] is the compression method, always 0 (deflate).
+                let compressed_text = &data[null_pos + 2..];
+                let text = latin1_to_string(&inflate_to_vec(compressed_text)?);
+                Ok(ChunkPayload::CompressedText { keyword, text })
+            }
+            "iTXt" => {
+                let keyword_end = find_null(data)?;
+                let keyword = latin1_to_string(&data[..keyword_end]);
+
+                let mut pos = keyword_end + 1;
+                if data.len() < pos + 2 {
+                    return Err("Chunk Parse Payload Error: iTXt missing compression flags".into());
+                }
+                let compressed = data[pos] == 1;
+                // data[pos + 1] is the compression method, always 0 (deflate).
+                pos += 2;
+
+                let lang_end = pos + find_null(&data[pos..])?;
+                let language_tag = String::from_utf8(data[pos..lang_end].to_vec())?;
+                pos = lang_end + 1;
+
+                let keyword_translated_end = pos + find_null(&data[pos..])?;
+                let translated_keyword = String::from_utf8(
+                    data[pos..keyword_translated_end].to_vec()
+                )?;
+                pos = keyword_translated_end + 1;
+
+                let text = if compressed {
+                    String::from_utf8(inflate_to_vec(&data[pos..])?)?
+                } else {
+                    String::from_utf8(data[pos..].to_vec())?
+                };
+
+                Ok(ChunkPayload::InternationalText {
+                    keyword,
+                    compressed,
+                    language_tag,
+                    translated_keyword,
+                    text,
+                })
+            }
+            other =>
+                Err(format!("Chunk Parse Payload Error: unsupported chunk type {}", other).into()),
+        }
+    }
+
+    /// Builds a `tEXt` chunk from a Latin-1 `keyword` and `text` pair.
+    pub fn text(keyword: &str, text: &str) -> Result<Chunk> {
+        reject_embedded_nul("keyword", keyword)?;
+        reject_embedded_nul("text", text)?;
+
+        let mut data = string_to_latin1(keyword)?;
+        data.push(0);
+        data.extend(string_to_latin1(text)?);
+        Ok(Chunk::new(ChunkType::from_str("tEXt")?, data))
+    }
+
+    /// Builds a `zTXt` chunk, zlib-compressing `text` (Latin-1).
+    pub fn ztext(keyword: &str, text: &str) -> Result<Chunk> {
+        reject_embedded_nul("keyword", keyword)?;
+        reject_embedded_nul("text", text)?;
+
+        let mut data = string_to_latin1(keyword)?;
+        data.push(0);
+        data.push(0); // compression method: deflate
+        data.extend(deflate_to_vec(&string_to_latin1(text)?)?);
+        Ok(Chunk::new(ChunkType::from_str("zTXt")?, data))
+    }
+
+    /// Builds an `iTXt` chunk. `text` is UTF-8 and, when `compressed` is
+    /// `true`, is zlib-deflated before being written.
+    pub fn itext(
+        keyword: &str,
+        language_tag: &str,
+        translated_keyword: &str,
+        text: &str,
+        compressed: bool
+    ) -> Result<Chunk> {
+        reject_embedded_nul("keyword", keyword)?;
+        reject_embedded_nul("language_tag", language_tag)?;
+        reject_embedded_nul("translated_keyword", translated_keyword)?;
+        reject_embedded_nul("text", text)?;
+
+        let mut data = string_to_latin1(keyword)?;
+        data.push(0);
+        data.push(compressed as u8);
+        data.push(0); // compression method: deflate
+        data.extend(language_tag.as_bytes());
+        data.push(0);
+        data.extend(translated_keyword.as_bytes());
+        data.push(0);
+        if compressed {
+            data.extend(deflate_to_vec(text.as_bytes())?);
+        } else {
+            data.extend(text.as_bytes());
+        }
+        Ok(Chunk::new(ChunkType::from_str("iTXt")?, data))
+    }
+}
+
+/// A decoded standard PNG text chunk, as returned by [`Chunk::parse_payload`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChunkPayload {
+    /// A `tEXt` chunk: an uncompressed Latin-1 keyword/text pair.
+    Text {
+        keyword: String,
+        text: String,
+    },
+    /// A `zTXt` chunk: a keyword paired with zlib-compressed Latin-1 text.
+    CompressedText {
+        keyword: String,
+        text: String,
+    },
+    /// An `iTXt` chunk: a Latin-1 keyword, a UTF-8 language tag and
+    /// translated keyword, plus UTF-8 text that may be zlib-compressed.
+    InternationalText {
+        keyword: String,
+        compressed: bool,
+        language_tag: String,
+        translated_keyword: String,
+        text: String,
+    },
+}
+
+fn find_null(data: &[u8]) -> Result<usize> {
+    data
+        .iter()
+        .position(|&b| b == 0)
+        .ok_or_else(|| "Chunk Parse Payload Error: missing null terminator".into())
+}
+
+fn latin1_to_string(data: &[u8]) -> String {
+    data
+        .iter()
+        .map(|&b| b as char)
+        .collect()
+}
+
+/// `parse_payload` uses the first `0` byte as a field delimiter, so a
+/// NUL-terminated field (keyword, language tag, translated keyword)
+/// containing an embedded NUL would silently shift the decoded boundaries
+/// instead of round-tripping. Every field, the trailing text included, is
+/// rejected up front if it contains a NUL.
+fn reject_embedded_nul(field_name: &str, s: &str) -> Result<()> {
+    if s.contains('\0') {
+        return Err(format!("Chunk Payload Error: {} contains an embedded NUL byte", field_name).into());
     }
+    Ok(())
+}
+
+fn string_to_latin1(s: &str) -> Result<Vec<u8>> {
+    s.chars()
+        .map(|c| {
+            let code = c as u32;
+            if code <= 0xff {
+                Ok(code as u8)
+            } else {
+                Err("Chunk Payload Error: text is not representable in Latin-1".into())
+            }
+        })
+        .collect()
+}
+
+fn inflate_to_vec(compressed: &[u8]) -> Result<Vec<u8>> {
+    use flate2::read::ZlibDecoder;
+
+    let mut decoder = ZlibDecoder::new(compressed);
+    let mut out = Vec::new();
+    decoder.read_to_end(&mut out)?;
+    Ok(out)
+}
+
+fn deflate_to_vec(data: &[u8]) -> Result<Vec<u8>> {
+    use flate2::Compression;
+    use flate2::write::ZlibEncoder;
+    use std::io::Write;
+
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(data)?;
+    Ok(encoder.finish()?)
 }
 
 impl TryFrom<&[u8]> for Chunk {
@@ -114,15 +415,10 @@ impl TryFrom<&[u8]> for Chunk {
         let chunk_type_bytes: [u8; 4] = [bytes[4], bytes[5], bytes[6], bytes[7]];
         let chunk_type_tmp = ChunkType::try_from(chunk_type_bytes)?;
 
-        let mut check_crc: Vec<u8> = vec![bytes[4], bytes[5], bytes[6], bytes[7]];
-
-        let mut data_tmp: Vec<u8> = vec![];
         let start = 8;
         let end = 8 + (len_tmp as usize);
-        for i in start..end {
-            data_tmp.push(bytes[i]);
-            check_crc.push(bytes[i]);
-        }
+        let data_slice = &bytes[start..end];
+        let data_tmp: Vec<u8> = data_slice.to_vec();
 
         let len_index = len_tmp as usize;
         let chunk_crc_bytes: [u8; 4] = [
@@ -134,8 +430,7 @@ impl TryFrom<&[u8]> for Chunk {
         let chunk_crc_tmp = u32::from_be_bytes(chunk_crc_bytes);
 
         // check crc
-        const CRC32: Crc<u32> = Crc::<u32>::new(&CRC_32_ISO_HDLC);
-        let checksum = CRC32.checksum(&check_crc);
+        let checksum = chunk_crc(&chunk_type_tmp, data_slice);
 
         if checksum != chunk_crc_tmp {
             return Err("Chunk Try From Error: crc not match".into());
@@ -144,7 +439,7 @@ impl TryFrom<&[u8]> for Chunk {
         let chunk_tmp = Chunk {
             len: len_tmp,
             chunktype: chunk_type_tmp,
-            chunk_data: data_tmp,
+            chunk_data: Bytes::from(data_tmp),
             chunk_crc: chunk_crc_tmp,
         };
         Ok(chunk_tmp)
@@ -167,6 +462,7 @@ impl fmt::Display for Chunk {
 mod tests {
     use super::*;
     use crate::chunk_type::ChunkType;
+    use std::io::BufReader;
     use std::str::FromStr;
 
     // #[test]
@@ -250,6 +546,122 @@ mod tests {
         assert_eq!(chunk.crc(), 2882656334);
     }
 
+    #[test]
+    fn test_valid_chunk_from_reader() {
+        let data_length: u32 = 42;
+        let chunk_type = "RuSt".as_bytes();
+        let message_bytes = "This is where your secret message will be!".as_bytes();
+        let crc: u32 = 2882656334;
+
+        let chunk_data: Vec<u8> = data_length
+            .to_be_bytes()
+            .iter()
+            .chain(chunk_type.iter())
+            .chain(message_bytes.iter())
+            .chain(crc.to_be_bytes().iter())
+            .copied()
+            .collect();
+
+        let mut reader = BufReader::new(chunk_data.as_slice());
+        let chunk = Chunk::from_reader(&mut reader).unwrap();
+
+        assert_eq!(chunk.length(), 42);
+        assert_eq!(chunk.chunk_type().to_string(), String::from("RuSt"));
+        assert_eq!(chunk.crc(), 2882656334);
+    }
+
+    #[test]
+    fn test_chunk_from_reader_rejects_oversized_length() {
+        let huge_length: u32 = MAX_CHUNK_LEN + 1;
+        let chunk_data = huge_length.to_be_bytes();
+
+        let mut reader = BufReader::new(chunk_data.as_slice());
+        let chunk = Chunk::from_reader(&mut reader);
+
+        assert!(chunk.is_err());
+    }
+
+    #[test]
+    fn test_encode_decode_round_trip() {
+        let chunk = testing_chunk();
+
+        let mut buf: Vec<u8> = vec![];
+        chunk.encode(&mut buf);
+        assert_eq!(buf, chunk.as_bytes());
+
+        let mut slice = buf.as_slice();
+        let decoded = Chunk::decode(&mut slice).unwrap();
+        assert_eq!(decoded.length(), chunk.length());
+        assert_eq!(decoded.chunk_type(), chunk.chunk_type());
+        assert_eq!(decoded.data(), chunk.data());
+        assert_eq!(decoded.crc(), chunk.crc());
+    }
+
+    #[test]
+    fn test_text_chunk_round_trip() {
+        let chunk = Chunk::text("Title", "A PNGme test image").unwrap();
+        assert_eq!(chunk.chunk_type().to_string(), "tEXt");
+
+        let payload = chunk.parse_payload().unwrap();
+        assert_eq!(payload, ChunkPayload::Text {
+            keyword: "Title".to_string(),
+            text: "A PNGme test image".to_string(),
+        });
+    }
+
+    #[test]
+    fn test_ztext_chunk_round_trip() {
+        let chunk = Chunk::ztext("Comment", "Some compressible text").unwrap();
+        assert_eq!(chunk.chunk_type().to_string(), "zTXt");
+
+        let payload = chunk.parse_payload().unwrap();
+        assert_eq!(payload, ChunkPayload::CompressedText {
+            keyword: "Comment".to_string(),
+            text: "Some compressible text".to_string(),
+        });
+    }
+
+    #[test]
+    fn test_itext_chunk_round_trip() {
+        let chunk = Chunk::itext("Title", "en", "Title", "hello world", true).unwrap();
+        assert_eq!(chunk.chunk_type().to_string(), "iTXt");
+
+        let payload = chunk.parse_payload().unwrap();
+        assert_eq!(payload, ChunkPayload::InternationalText {
+            keyword: "Title".to_string(),
+            compressed: true,
+            language_tag: "en".to_string(),
+            translated_keyword: "Title".to_string(),
+            text: "hello world".to_string(),
+        });
+    }
+
+    #[test]
+    fn test_parse_payload_rejects_unsupported_chunk_type() {
+        let chunk = testing_chunk();
+        assert!(chunk.parse_payload().is_err());
+    }
+
+    #[test]
+    fn test_text_chunk_rejects_embedded_nul() {
+        assert!(Chunk::text("Ti\0tle", "body").is_err());
+        assert!(Chunk::text("Title", "bo\0dy").is_err());
+    }
+
+    #[test]
+    fn test_ztext_chunk_rejects_embedded_nul() {
+        assert!(Chunk::ztext("Comm\0ent", "body").is_err());
+        assert!(Chunk::ztext("Comment", "bo\0dy").is_err());
+    }
+
+    #[test]
+    fn test_itext_chunk_rejects_embedded_nul() {
+        assert!(Chunk::itext("Ti\0tle", "en", "Title", "hello", true).is_err());
+        assert!(Chunk::itext("Title", "e\0n", "Title", "hello", true).is_err());
+        assert!(Chunk::itext("Title", "en", "Ti\0tle", "hello", true).is_err());
+        assert!(Chunk::itext("Title", "en", "Title", "hel\0lo", true).is_err());
+    }
+
     #[test]
     fn test_invalid_chunk_from_bytes() {
         let data_length: u32 = 42;
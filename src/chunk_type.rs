@@ -34,6 +34,52 @@ impl ChunkType {
     pub fn is_valid(&self) -> bool {
         self.is_reserved_bit_valid()
     }
+
+    /// Starts a [`ChunkTypeBuilder`] for a custom chunk type, seeded with
+    /// `base`'s four letters. Use the builder's methods to set the
+    /// critical/public/safe-to-copy property bits by semantics instead of
+    /// hand-casing the letters.
+    pub fn builder(base: [u8; 4]) -> ChunkTypeBuilder {
+        ChunkTypeBuilder { type_bytes: base }
+    }
+}
+
+/// Builds a [`ChunkType`] by setting its property bits by semantics rather
+/// than by hand-casing the four letters of a chunk type.
+pub struct ChunkTypeBuilder {
+    type_bytes: [u8; 4],
+}
+
+impl ChunkTypeBuilder {
+    /// Sets whether this chunk type is critical (uppercase first letter).
+    pub fn critical(mut self, critical: bool) -> Self {
+        self.type_bytes[0] = set_case(self.type_bytes[0], critical);
+        self
+    }
+
+    /// Sets whether this chunk type is public (uppercase second letter).
+    pub fn public(mut self, public: bool) -> Self {
+        self.type_bytes[1] = set_case(self.type_bytes[1], public);
+        self
+    }
+
+    /// Sets whether this chunk type is safe to copy (lowercase fourth letter).
+    pub fn safe_to_copy(mut self, safe_to_copy: bool) -> Self {
+        self.type_bytes[3] = set_case(self.type_bytes[3], !safe_to_copy);
+        self
+    }
+
+    /// Validates the four letters and builds the `ChunkType`. The reserved
+    /// bit (third letter) is always forced uppercase, so the result always
+    /// satisfies `is_reserved_bit_valid`.
+    pub fn build(mut self) -> Result<ChunkType> {
+        self.type_bytes[2] = self.type_bytes[2].to_ascii_uppercase();
+        ChunkType::try_from(self.type_bytes)
+    }
+}
+
+fn set_case(byte: u8, uppercase: bool) -> u8 {
+    if uppercase { byte.to_ascii_uppercase() } else { byte.to_ascii_lowercase() }
 }
 
 impl TryFrom<[u8; 4]> for ChunkType {
@@ -165,6 +211,28 @@ mod tests {
         assert!(chunk.is_err());
     }
 
+    #[test]
+    pub fn test_chunk_type_builder() {
+        let chunk = ChunkType::builder(*b"rust")
+            .critical(true)
+            .public(false)
+            .safe_to_copy(true)
+            .build()
+            .unwrap();
+
+        assert_eq!(&chunk.to_string(), "RuSt");
+        assert!(chunk.is_critical());
+        assert!(!chunk.is_public());
+        assert!(chunk.is_reserved_bit_valid());
+        assert!(chunk.is_safe_to_copy());
+    }
+
+    #[test]
+    pub fn test_chunk_type_builder_rejects_non_alphabetic_base() {
+        let chunk = ChunkType::builder(*b"ru1t").critical(true).build();
+        assert!(chunk.is_err());
+    }
+
     #[test]
     pub fn test_chunk_type_string() {
         let chunk = ChunkType::from_str("RuSt").unwrap();